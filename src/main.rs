@@ -1,8 +1,13 @@
-use clap::Parser;
+use bio::io::fastq;
+use clap::{Parser, ValueEnum};
 use rust_htslib::{
-    bam::{self, Format, Header, Read, Record},
+    bam::{self, record::Aux, Format, Header, IndexedRead, Read, Record},
     htslib::htsFormat,
+    tpool::ThreadPool,
 };
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 
 fn get_file_format(hst_format: u32) -> Result<Format, String> {
     // formats (taken from htslib/hts.h enum htsExactFormat)
@@ -14,6 +19,30 @@ fn get_file_format(hst_format: u32) -> Result<Format, String> {
     }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Sam,
+    Bam,
+    Cram,
+}
+
+impl From<OutputFormat> for Format {
+    fn from(output_format: OutputFormat) -> Self {
+        match output_format {
+            OutputFormat::Sam => Format::Sam,
+            OutputFormat::Bam => Format::Bam,
+            OutputFormat::Cram => Format::Cram,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReductionMode {
+    Flat,
+    Ramp,
+    Cap,
+}
+
 fn reduce_single_qual(q: u8, qual_reduction: &u8) -> u8 {
     let q_reduced: u8;
     if q >= *qual_reduction {
@@ -24,12 +53,39 @@ fn reduce_single_qual(q: u8, qual_reduction: &u8) -> u8 {
     q_reduced
 }
 
+/// Reduce the quality of a single edge base, `distance` positions in from
+/// the (clip-adjusted) read end, according to `mode`.
+fn reduce_qual_at_distance(
+    q: u8,
+    qual_reduction: &u8,
+    num_bases: &usize,
+    distance: usize,
+    mode: ReductionMode,
+) -> u8 {
+    match mode {
+        ReductionMode::Flat => reduce_single_qual(q, qual_reduction),
+        ReductionMode::Ramp => {
+            // With num_bases == 0 there's no "real" window, so every base
+            // this gets called on is a soft-clip-only position (distance
+            // always 0); treat that the same as the other modes do and
+            // apply the full reduction, instead of dividing by zero.
+            let window = (*num_bases).max(1);
+            let remaining = window.saturating_sub(distance);
+            let ramped_reduction =
+                (*qual_reduction as f64 * remaining as f64 / window as f64).round() as u8;
+            reduce_single_qual(q, &ramped_reduction)
+        }
+        ReductionMode::Cap => q.min(*qual_reduction),
+    }
+}
+
 fn reduce_qualities_in_edges(
     mut qual: Vec<u8>,
     num_bases: &usize,
     qual_reduction: &u8,
     leading_softclips: &usize,
     trailing_softclips: &usize,
+    mode: ReductionMode,
 ) -> Vec<u8> {
     let seq_len = qual.len() as usize;
 
@@ -38,7 +94,8 @@ fn reduce_qualities_in_edges(
         if pos >= seq_len {
             continue;
         };
-        qual[pos] = reduce_single_qual(qual[pos], qual_reduction);
+        let distance = pos.saturating_sub(*leading_softclips);
+        qual[pos] = reduce_qual_at_distance(qual[pos], qual_reduction, num_bases, distance, mode);
     }
 
     let num_bases_with_clip = *num_bases + *trailing_softclips;
@@ -47,14 +104,142 @@ fn reduce_qualities_in_edges(
         if pos_from_end < *num_bases {
             continue;
         }
-        qual[pos_from_end] = reduce_single_qual(qual[pos_from_end], qual_reduction);
+        let distance = pos.saturating_sub(*trailing_softclips);
+        qual[pos_from_end] =
+            reduce_qual_at_distance(qual[pos_from_end], qual_reduction, num_bases, distance, mode);
     }
     qual
 }
 
-fn reduce_qualities_in_read(record: &mut Record, num_bases: &usize, qual_reduction: &u8) {
+/// Which records are eligible for edge-quality trimming; records that don't
+/// pass are still written out, just left untouched.
+struct RecordFilter {
+    skip_secondary: bool,
+    skip_supplementary: bool,
+    skip_unmapped: bool,
+    skip_dup: bool,
+    min_mapq: u8,
+}
+
+impl RecordFilter {
+    fn selects(&self, record: &Record) -> bool {
+        if self.skip_secondary && record.is_secondary() {
+            return false;
+        }
+        if self.skip_supplementary && record.is_supplementary() {
+            return false;
+        }
+        if self.skip_unmapped && record.is_unmapped() {
+            return false;
+        }
+        if self.skip_dup && record.is_duplicate() {
+            return false;
+        }
+        if record.mapq() < self.min_mapq {
+            return false;
+        }
+        true
+    }
+}
+
+fn store_original_qualities(
+    record: &mut Record,
+    qual: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Don't clobber an OQ tag that an upstream tool already wrote.
+    if record.aux(b"OQ").is_ok() {
+        return Ok(());
+    }
+    // htslib represents a missing quality string (SAM QUAL == "*") as all
+    // 0xFF bytes; there's nothing meaningful to preserve in that case, and
+    // `q + 33` would overflow `u8`.
+    if qual.iter().all(|&q| q == 0xFF) {
+        return Ok(());
+    }
+    let oq: String = qual.iter().map(|&q| (q + 33) as char).collect();
+    record.push_aux(b"OQ", Aux::String(&oq))?;
+    Ok(())
+}
+
+/// Read a BED file into `chrom:start-end` region strings (BED is 0-based
+/// half-open, htslib regions are 1-based inclusive).
+fn read_bed_regions(bed_path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let file = File::open(bed_path)?;
+    let mut regions = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("track") {
+            continue;
+        }
+        let mut fields = line.split('\t');
+        let chrom = fields.next().ok_or("Malformed BED line")?;
+        let start: u64 = fields.next().ok_or("Malformed BED line")?.parse()?;
+        let end: u64 = fields.next().ok_or("Malformed BED line")?.parse()?;
+        regions.push(format!("{chrom}:{}-{end}", start + 1));
+    }
+    Ok(regions)
+}
+
+/// Identifies one specific alignment record, not just a read name, so a
+/// fetched mate/secondary/supplementary at a different locus is never
+/// confused with another alignment of the same read.
+type AlignmentKey = (i32, i64, u16, Vec<u8>);
+
+fn alignment_key(record: &Record) -> AlignmentKey {
+    (
+        record.tid(),
+        record.pos(),
+        record.flags(),
+        record.qname().to_owned(),
+    )
+}
+
+/// Use the BAM index to find which individual alignments overlap the
+/// requested regions, without having to decode every record's
+/// CIGAR/qualities up front. This still requires one linear pass over the
+/// whole file afterwards to produce a complete output BAM, but it skips the
+/// (more expensive) CIGAR/quality decoding and edge-trimming for every
+/// record that isn't actually in a requested region.
+fn collect_region_alignments(
+    input_bam: &str,
+    regions: &[String],
+) -> Result<HashSet<AlignmentKey>, Box<dyn std::error::Error>> {
+    if input_bam == "-" {
+        return Err(
+            "--region/--bed restricted trimming requires a seekable, indexed file; \
+             stdin (\"-\") is not supported"
+                .into(),
+        );
+    }
+    let mut indexed_reader = bam::IndexedReader::from_path(input_bam)
+        .map_err(|_| "No .bai/.csi index found for --region/--bed restricted trimming")?;
+
+    let mut keys = HashSet::new();
+    let mut record = Record::new();
+    for region in regions {
+        indexed_reader.fetch(region.as_str())?;
+        while let Some(r) = indexed_reader.read(&mut record) {
+            r?;
+            keys.insert(alignment_key(&record));
+        }
+    }
+    Ok(keys)
+}
+
+fn reduce_qualities_in_read(
+    record: &mut Record,
+    num_bases: &usize,
+    qual_reduction: &u8,
+    store_oq: bool,
+    mode: ReductionMode,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut qual = record.qual().to_vec();
 
+    if store_oq {
+        store_original_qualities(record, &qual)?;
+    }
+
     let cigar_view = record.cigar();
     let leadling_softclips = cigar_view.leading_softclips() as usize;
     let trailing_softclips = cigar_view.trailing_softclips() as usize;
@@ -65,12 +250,14 @@ fn reduce_qualities_in_read(record: &mut Record, num_bases: &usize, qual_reducti
         qual_reduction,
         &leadling_softclips,
         &trailing_softclips,
+        mode,
     );
 
     let cigar = cigar_view.take();
     let mut seq = record.seq().as_bytes();
     let qname = record.qname().to_owned();
     record.set(&qname, Some(&cigar), &mut seq, &qual);
+    Ok(())
 }
 
 fn trim_qualities_from_edges_in_bam(
@@ -78,7 +265,22 @@ fn trim_qualities_from_edges_in_bam(
     output_bam: &str,
     num_bases: &usize,
     qual_reduction: &u8,
+    threads: usize,
+    reference: &Option<String>,
+    output_format: &Option<OutputFormat>,
+    store_oq: bool,
+    filter: &RecordFilter,
+    regions: &[String],
+    mode: ReductionMode,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // Narrow trimming down to the alignments overlapping the requested
+    // regions, using the index instead of decoding every record up front.
+    let region_alignments = if regions.is_empty() {
+        None
+    } else {
+        Some(collect_region_alignments(input_bam, regions)?)
+    };
+
     let mut reader = match input_bam {
         "-" => bam::Reader::from_stdin()?,
         _ => bam::Reader::from_path(input_bam)?,
@@ -92,7 +294,17 @@ fn trim_qualities_from_edges_in_bam(
     if hst_format.category != 1 {
         return Err("The file is not recognized as sequence data".into());
     }
-    let format = get_file_format(hst_format.format)?;
+    let input_format = get_file_format(hst_format.format)?;
+    let format = match output_format {
+        Some(output_format) => Format::from(*output_format),
+        None => input_format,
+    };
+
+    if let Some(reference) = reference {
+        reader.set_reference(reference)?;
+    } else if matches!(input_format, Format::Cram) {
+        return Err("CRAM input requires a --reference fasta to decode".into());
+    }
 
     let header_view = reader.header();
     let header = Header::from_template(header_view);
@@ -102,20 +314,80 @@ fn trim_qualities_from_edges_in_bam(
         _ => bam::Writer::from_path(output_bam, &header, format)?,
     };
 
+    if let Some(reference) = reference {
+        if matches!(format, Format::Cram) {
+            writer.set_reference(reference)?;
+        }
+    }
+
+    // Share a single htslib thread pool between the reader and the writer so
+    // decompression, quality editing and recompression can overlap.
+    if threads > 0 {
+        let tpool = ThreadPool::new(threads as u32)?;
+        reader.set_thread_pool(&tpool)?;
+        writer.set_thread_pool(&tpool)?;
+    }
+
     // Iterate through all records
     let mut record = Record::new();
     while let Some(r) = reader.read(&mut record) {
         r.expect("Failed to parse record");
 
-        reduce_qualities_in_read(&mut record, num_bases, qual_reduction);
+        let in_region = match &region_alignments {
+            Some(keys) => keys.contains(&alignment_key(&record)),
+            None => true,
+        };
+
+        if in_region && filter.selects(&record) {
+            reduce_qualities_in_read(&mut record, num_bases, qual_reduction, store_oq, mode)?;
+        }
 
-        // Write the modified record to the output BAM file
+        // Write the (possibly unmodified) record to the output BAM file
         writer.write(&record)?;
     }
 
     Ok(())
 }
 
+/// Recognize FASTQ inputs/outputs by extension. Gzipped FASTQ isn't
+/// supported (`fastq::Reader`/`Writer` read/write plain text), so a
+/// `.fastq.gz`/`.fq.gz` path is deliberately not matched here.
+fn looks_like_fastq(path: &str) -> bool {
+    path.ends_with(".fastq") || path.ends_with(".fq")
+}
+
+fn trim_qualities_from_edges_in_fastq(
+    input_fastq: &str,
+    output_fastq: &str,
+    num_bases: &usize,
+    qual_reduction: &u8,
+    mode: ReductionMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = match input_fastq {
+        "-" => fastq::Reader::new(io::stdin()),
+        _ => fastq::Reader::from_file(input_fastq)?,
+    };
+
+    let mut writer = match output_fastq {
+        "-" => fastq::Writer::new(io::stdout()),
+        _ => fastq::Writer::to_file(output_fastq)?,
+    };
+
+    for result in reader.records() {
+        let record = result?;
+
+        // No CIGAR is available before alignment, so there are no soft clips.
+        let qual: Vec<u8> = record.qual().iter().map(|q| q - 33).collect();
+        let qual = reduce_qualities_in_edges(qual, num_bases, qual_reduction, &0, &0, mode);
+        let qual: Vec<u8> = qual.into_iter().map(|q| q + 33).collect();
+
+        let trimmed = fastq::Record::with_attrs(record.id(), record.desc(), record.seq(), &qual);
+        writer.write_record(&trimmed)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Parser)]
 #[command(
     name = "trim_quals",
@@ -138,6 +410,58 @@ struct Cli {
     /// quality reduction factor (default: 20)
     #[arg(long, default_value_t = 20)]
     qual_reduction: u8,
+
+    /// number of threads to use for BGZF (de)compression (default: 0, single-threaded)
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// reference fasta used to decode/encode CRAM
+    #[arg(long)]
+    reference: Option<String>,
+
+    /// force the output format instead of reusing the input format
+    #[arg(long, value_enum)]
+    output_format: Option<OutputFormat>,
+
+    /// store the pre-trimming qualities in the OQ aux tag
+    #[arg(long, default_value_t = false)]
+    store_oq: bool,
+
+    /// don't trim secondary alignments
+    #[arg(long, default_value_t = false)]
+    skip_secondary: bool,
+
+    /// don't trim supplementary alignments
+    #[arg(long, default_value_t = false)]
+    skip_supplementary: bool,
+
+    /// don't trim unmapped reads
+    #[arg(long, default_value_t = false)]
+    skip_unmapped: bool,
+
+    /// don't trim reads flagged as PCR/optical duplicates
+    #[arg(long, default_value_t = false)]
+    skip_dup: bool,
+
+    /// only trim reads with at least this mapping quality (default: 0)
+    #[arg(long, default_value_t = 0)]
+    min_mapq: u8,
+
+    /// restrict trimming to a region "chr:start-end" (repeatable, requires a .bai/.csi index)
+    #[arg(long)]
+    region: Vec<String>,
+
+    /// restrict trimming to the regions in a BED file (requires a .bai/.csi index)
+    #[arg(long)]
+    bed: Option<String>,
+
+    /// shape of the quality reduction applied to edge bases
+    #[arg(long, value_enum, default_value = "flat")]
+    mode: ReductionMode,
+
+    /// treat input/output as FASTQ instead of Sam/Bam/Cram (default: detected from the file extension)
+    #[arg(long, default_value_t = false)]
+    fastq: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -147,9 +471,43 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let output_bam = &args.output_bam;
     let num_bases: usize = args.num_bases;
     let qual_reduction: u8 = args.qual_reduction;
+    let threads: usize = args.threads;
+
+    if args.fastq || looks_like_fastq(input_bam) || looks_like_fastq(output_bam) {
+        return trim_qualities_from_edges_in_fastq(
+            input_bam,
+            output_bam,
+            &num_bases,
+            &qual_reduction,
+            args.mode,
+        );
+    }
+
+    let mut regions = args.region.clone();
+    if let Some(bed) = &args.bed {
+        regions.extend(read_bed_regions(bed)?);
+    }
 
     // Call the quality reduction function
-    trim_qualities_from_edges_in_bam(input_bam, output_bam, &num_bases, &qual_reduction)?;
+    trim_qualities_from_edges_in_bam(
+        input_bam,
+        output_bam,
+        &num_bases,
+        &qual_reduction,
+        threads,
+        &args.reference,
+        &args.output_format,
+        args.store_oq,
+        &RecordFilter {
+            skip_secondary: args.skip_secondary,
+            skip_supplementary: args.skip_supplementary,
+            skip_unmapped: args.skip_unmapped,
+            skip_dup: args.skip_dup,
+            min_mapq: args.min_mapq,
+        },
+        &regions,
+        args.mode,
+    )?;
 
     Ok(())
 }
@@ -157,6 +515,141 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+
+    /// Write `content` to a temp file whose name includes `name` and return
+    /// its path, so `read_bed_regions` can be exercised end-to-end.
+    fn write_temp_bed(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("trim_quals_test_{name}.bed"));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_read_bed_regions() {
+        let path = write_temp_bed(
+            "basic",
+            "# comment\n\ntrack name=example\nchr1\t99\t120\nchr2\t0\t10\n",
+        );
+        let regions = read_bed_regions(&path).unwrap();
+        assert_eq!(regions, vec!["chr1:100-120", "chr2:1-10"]);
+    }
+
+    #[test]
+    fn test_read_bed_regions_malformed_line() {
+        let path = write_temp_bed("malformed", "chr1\t10\n");
+        assert!(read_bed_regions(&path).is_err());
+    }
+
+    fn new_record(qual: &[u8]) -> Record {
+        let mut record = Record::new();
+        let seq = vec![b'A'; qual.len()];
+        record.set(b"read1", None, &seq, qual);
+        record
+    }
+
+    #[test]
+    fn test_store_original_qualities_writes_oq() {
+        let mut record = new_record(&[10, 20, 30]);
+        let qual = record.qual().to_vec();
+        store_original_qualities(&mut record, &qual).unwrap();
+        match record.aux(b"OQ").unwrap() {
+            Aux::String(oq) => assert_eq!(oq, "+5?"),
+            other => panic!("expected Aux::String, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_store_original_qualities_keeps_existing_oq() {
+        let mut record = new_record(&[10, 20, 30]);
+        record.push_aux(b"OQ", Aux::String("already-there")).unwrap();
+        let qual = record.qual().to_vec();
+        store_original_qualities(&mut record, &qual).unwrap();
+        match record.aux(b"OQ").unwrap() {
+            Aux::String(oq) => assert_eq!(oq, "already-there"),
+            other => panic!("expected Aux::String, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_store_original_qualities_skips_missing_quality() {
+        // Setting an empty qual slice on a record with a real sequence
+        // makes rust-htslib store the SAM "*" (no quality) sentinel, which
+        // reads back as all 0xFF bytes, one per base.
+        let mut record = Record::new();
+        record.set(b"read1", None, b"ACGT", &[]);
+        let qual = record.qual().to_vec();
+        assert_eq!(qual, vec![0xFF; 4]);
+        store_original_qualities(&mut record, &qual).unwrap();
+        assert!(record.aux(b"OQ").is_err());
+    }
+
+    fn filter_with(
+        skip_secondary: bool,
+        skip_supplementary: bool,
+        skip_unmapped: bool,
+        skip_dup: bool,
+        min_mapq: u8,
+    ) -> RecordFilter {
+        RecordFilter {
+            skip_secondary,
+            skip_supplementary,
+            skip_unmapped,
+            skip_dup,
+            min_mapq,
+        }
+    }
+
+    #[test]
+    fn test_record_filter_selects_plain_record_by_default() {
+        let record = new_record(&[30, 30, 30]);
+        let filter = filter_with(false, false, false, false, 0);
+        assert!(filter.selects(&record));
+    }
+
+    #[test]
+    fn test_record_filter_skip_secondary() {
+        let mut record = new_record(&[30, 30, 30]);
+        record.set_secondary();
+        let filter = filter_with(true, false, false, false, 0);
+        assert!(!filter.selects(&record));
+    }
+
+    #[test]
+    fn test_record_filter_skip_supplementary() {
+        let mut record = new_record(&[30, 30, 30]);
+        record.set_supplementary();
+        let filter = filter_with(false, true, false, false, 0);
+        assert!(!filter.selects(&record));
+    }
+
+    #[test]
+    fn test_record_filter_skip_unmapped() {
+        let mut record = new_record(&[30, 30, 30]);
+        record.set_unmapped();
+        let filter = filter_with(false, false, true, false, 0);
+        assert!(!filter.selects(&record));
+    }
+
+    #[test]
+    fn test_record_filter_skip_dup() {
+        let mut record = new_record(&[30, 30, 30]);
+        record.set_duplicate();
+        let filter = filter_with(false, false, false, true, 0);
+        assert!(!filter.selects(&record));
+    }
+
+    #[test]
+    fn test_record_filter_min_mapq() {
+        let mut record = new_record(&[30, 30, 30]);
+        record.set_mapq(10);
+        let filter = filter_with(false, false, false, false, 20);
+        assert!(!filter.selects(&record));
+
+        let filter = filter_with(false, false, false, false, 10);
+        assert!(filter.selects(&record));
+    }
 
     #[test]
     fn test_reduce_qualities_in_edges() {
@@ -164,7 +657,14 @@ mod tests {
         let num_bases = 2;
         let qual_reduction = 5;
         let expected = vec![25, 20, 20, 15, 5, 0];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -174,7 +674,14 @@ mod tests {
         let num_bases = 2;
         let qual_reduction = 0;
         let expected = vec![30, 25, 20, 15, 10, 5];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -184,7 +691,14 @@ mod tests {
         let num_bases = 3;
         let qual_reduction = 40;
         let expected = vec![0, 0, 0, 0, 0, 0];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -194,7 +708,14 @@ mod tests {
         let num_bases = 4;
         let qual_reduction = 10;
         let expected = vec![20, 15, 10, 5, 0, 0];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -204,7 +725,14 @@ mod tests {
         let num_bases = 2;
         let qual_reduction = 5;
         let expected = vec![];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -214,7 +742,14 @@ mod tests {
         let num_bases = 4;
         let qual_reduction = 10;
         let expected = vec![20, 15, 10];
-        let result = reduce_qualities_in_edges(qual, &num_bases, &qual_reduction, &0, &0);
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Flat,
+        );
         assert_eq!(result, expected);
     }
 
@@ -232,6 +767,295 @@ mod tests {
             &qual_reduction,
             &leading_softclips,
             &trailing_softclips,
+            ReductionMode::Flat,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_num_bases_zero_with_soft_clips() {
+        // num_bases == 0 means there's no "real" trimming window, but
+        // soft-clipped bases should still be fully reduced in every mode.
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 0;
+        let leading_softclips = 2;
+        let trailing_softclips = 1;
+        let qual_reduction = 5;
+
+        let expected_flat = vec![25, 20, 20, 15, 10, 0];
+        let result = reduce_qualities_in_edges(
+            qual.clone(),
+            &num_bases,
+            &qual_reduction,
+            &leading_softclips,
+            &trailing_softclips,
+            ReductionMode::Flat,
+        );
+        assert_eq!(result, expected_flat);
+
+        let expected_ramp = vec![25, 20, 20, 15, 10, 0];
+        let result = reduce_qualities_in_edges(
+            qual.clone(),
+            &num_bases,
+            &qual_reduction,
+            &leading_softclips,
+            &trailing_softclips,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected_ramp);
+
+        let expected_cap = vec![5, 5, 20, 15, 10, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &leading_softclips,
+            &trailing_softclips,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected_cap);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 2;
+        let qual_reduction = 5;
+        let expected = vec![25, 22, 20, 15, 7, 0];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_overlaping_edges_ramp_mode() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 4;
+        let qual_reduction = 10;
+        let expected = vec![20, 17, 15, 12, 2, 0];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode_no_reduction() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 2;
+        let qual_reduction = 0;
+        let expected = vec![30, 25, 20, 15, 10, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode_full_reduction() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 3;
+        let qual_reduction = 255;
+        let expected = vec![0, 0, 0, 0, 0, 0];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode_empty_qual() {
+        let qual = vec![];
+        let num_bases = 2;
+        let qual_reduction = 5;
+        let expected = vec![];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode_num_bases_exceeds_length() {
+        let qual = vec![30, 25, 20];
+        let num_bases = 4;
+        let qual_reduction = 10;
+        let expected = vec![20, 17, 15];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_ramp_mode_with_soft_clips() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 1;
+        let leading_softclips = 1;
+        let trailing_softclips = 2;
+        let qual_reduction = 5;
+        let expected = vec![25, 20, 20, 10, 5, 0];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &leading_softclips,
+            &trailing_softclips,
+            ReductionMode::Ramp,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 2;
+        let qual_reduction = 5;
+        let expected = vec![5, 5, 20, 15, 5, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_overlaping_edges_cap_mode() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 4;
+        let qual_reduction = 10;
+        let expected = vec![10, 10, 10, 10, 10, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode_no_reduction() {
+        // A cap above every existing quality leaves the read untouched.
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 2;
+        let qual_reduction = 100;
+        let expected = vec![30, 25, 20, 15, 10, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode_full_reduction() {
+        // A cap of 0 clamps every edge base down to 0.
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 3;
+        let qual_reduction = 0;
+        let expected = vec![0, 0, 0, 0, 0, 0];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode_empty_qual() {
+        let qual = vec![];
+        let num_bases = 2;
+        let qual_reduction = 5;
+        let expected = vec![];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode_num_bases_exceeds_length() {
+        let qual = vec![30, 25, 20];
+        let num_bases = 4;
+        let qual_reduction = 10;
+        let expected = vec![10, 10, 10];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &0,
+            &0,
+            ReductionMode::Cap,
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_reduce_qualities_in_edges_cap_mode_with_soft_clips() {
+        let qual = vec![30, 25, 20, 15, 10, 5];
+        let num_bases = 1;
+        let leading_softclips = 1;
+        let trailing_softclips = 2;
+        let qual_reduction = 5;
+        let expected = vec![5, 5, 20, 5, 5, 5];
+        let result = reduce_qualities_in_edges(
+            qual,
+            &num_bases,
+            &qual_reduction,
+            &leading_softclips,
+            &trailing_softclips,
+            ReductionMode::Cap,
         );
         assert_eq!(result, expected);
     }